@@ -4,15 +4,32 @@
 // LICENSE file in the "hack" directory of this source tree.
 
 use super::{Class, Error, Result};
-use crate::decl_defs::FoldedClass;
+use crate::decl_defs::{FoldedClass, Subst};
 use crate::folded_decl_provider::FoldedDeclProvider;
 use crate::reason::Reason;
-use crate::typing_defs::ClassElt;
+use crate::typing_defs::{ClassElt, DeclTy, DeclTy_, FunParam, FunType, ShapeFieldType, ShapeType};
 use dashmap::DashMap;
-use once_cell::sync::OnceCell;
-use pos::{BuildMethodNameHasher, BuildPropNameHasher, MethodName, PropName, TypeName};
+use pos::{
+    BuildClassConstNameHasher, BuildMethodNameHasher, BuildPropNameHasher,
+    BuildTypeConstNameHasher, BuildTypeNameHasher, ClassConstName, MethodName, PropName,
+    TypeConstName, TypeName,
+};
 use std::fmt;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+/// The member kinds that `ClassType`'s member-cache can hold, used to address
+/// a single cache entry for invalidation.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Member {
+    Prop(PropName),
+    StaticProp(PropName),
+    Method(MethodName),
+    StaticMethod(MethodName),
+    Constructor,
+    Const(ClassConstName),
+    TypeConst(TypeConstName),
+}
 
 #[derive(Debug)]
 struct EagerMembers<R: Reason> {
@@ -20,7 +37,27 @@ struct EagerMembers<R: Reason> {
     static_props: DashMap<PropName, Arc<ClassElt<R>>, BuildPropNameHasher>,
     methods: DashMap<MethodName, Arc<ClassElt<R>>, BuildMethodNameHasher>,
     static_methods: DashMap<MethodName, Arc<ClassElt<R>>, BuildMethodNameHasher>,
-    constructor: OnceCell<Option<Arc<ClassElt<R>>>>,
+    // A nested `Option`: the outer `Option` is `None` until the constructor
+    // has been looked up for the first time (or until invalidated), while the
+    // inner `Option` records whether the class actually has a constructor.
+    constructor: RwLock<Option<Option<Arc<ClassElt<R>>>>>,
+    consts: DashMap<ClassConstName, Arc<ClassElt<R>>, BuildClassConstNameHasher>,
+    type_consts: DashMap<TypeConstName, Arc<ClassElt<R>>, BuildTypeConstNameHasher>,
+    // Reverse index from a member's origin class to the `Member`s cached here
+    // that came from it, so a `TypingDeclProvider` reacting to "this origin's
+    // shallow decl changed" can invalidate just the affected entries instead
+    // of every cached member.
+    by_origin: DashMap<TypeName, Vec<Member>, BuildTypeNameHasher>,
+    // Bumped by every `invalidate_member`/`invalidate_origin`/`invalidate_all`
+    // call. A fetch that started before some invalidation ran can otherwise
+    // finish after it and silently repopulate the cache with a value that's
+    // now stale (the provider was asked for the pre-edit type, and nothing
+    // else would notice the insert happened "too late"). Each fetch snapshots
+    // this counter before calling the provider and re-checks it just before
+    // inserting; a mismatch means an invalidation raced it, so it skips the
+    // insert (and the `remember_origin` that would resurrect `by_origin`)
+    // and just returns the freshly-fetched value for this one call.
+    epoch: AtomicU64,
 }
 
 /// A typing `ClassType` (c.f. the `Eager` variant of OCaml type
@@ -31,7 +68,16 @@ struct EagerMembers<R: Reason> {
 /// duplication). When asked for a class member, the `ClassType` checks its
 /// member-cache. If not present, it looks up the type of the member using the
 /// `FoldedDeclProvider`, and populates its member-cache with a new `ClassElt`
-/// containing that type and any other metadata from the `FoldedElt`.
+/// containing that type and any other metadata from the `FoldedElt`. For
+/// members inherited from an ancestor class, the fetched type is expressed in
+/// terms of the ancestor's type parameters; it is instantiated using
+/// `self.class.substs` before being cached, so that callers always see types
+/// expressed in terms of `self.class`'s own type parameters.
+///
+/// Cached members are invalidated individually via `invalidate_member`, by
+/// origin class via `invalidate_origin`, or all at once via `invalidate_all`,
+/// so that a long-lived `ClassType` can be kept in sync as the shallow decls
+/// it was derived from change.
 pub struct ClassType<R: Reason> {
     provider: Arc<dyn FoldedDeclProvider<R>>,
     class: Arc<FoldedClass<R>>,
@@ -40,7 +86,14 @@ pub struct ClassType<R: Reason> {
 
 impl<R: Reason> fmt::Debug for ClassType<R> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        self.fetch_all_members().unwrap();
+        // Populate the cache so every member is visible in the output. A
+        // `MemberTypeMissing` error (a provider hiccup or an invalidation
+        // race, not a bug in `Debug` itself) must not crash whatever process
+        // is formatting this value; in that case only the error is printed,
+        // not the class or its members.
+        if let Err(e) = self.fetch_all_members() {
+            return write!(f, "ClassType {{ <error fetching members: {e}> }}");
+        }
         f.debug_struct("ClassType")
             .field("class", &self.class)
             .field("members", &self.members)
@@ -55,7 +108,11 @@ impl<R: Reason> EagerMembers<R> {
             static_props: DashMap::default(),
             methods: DashMap::default(),
             static_methods: DashMap::default(),
-            constructor: OnceCell::new(),
+            constructor: RwLock::new(None),
+            consts: DashMap::default(),
+            type_consts: DashMap::default(),
+            by_origin: DashMap::default(),
+            epoch: AtomicU64::new(0),
         }
     }
 }
@@ -69,6 +126,200 @@ impl<R: Reason> ClassType<R> {
         }
     }
 
+    /// Every property declared on or inherited by this class, together with
+    /// its resolved (and, for inherited props, substituted) type.
+    /// `self.class.props` is the folded view of the class's props, so it
+    /// already contains exactly one entry per name, with any override in
+    /// `self.class` shadowing the ancestor definition it overrides.
+    ///
+    /// A `MemberTypeMissing` error for any one member fails the whole call
+    /// rather than being dropped, since callers like "implements all
+    /// abstract members" checks must not mistake a provider hiccup on one
+    /// member for that member's absence.
+    pub fn all_props(&self) -> Result<Vec<(PropName, Arc<ClassElt<R>>)>> {
+        self.class
+            .props
+            .iter()
+            .filter_map(|(&name, _)| match self.get_prop(name) {
+                Ok(Some(elt)) => Some(Ok((name, elt))),
+                Ok(None) => None,
+                Err(e) => Some(Err(e)),
+            })
+            .collect()
+    }
+
+    /// Every method declared on or inherited by this class. See `all_props`
+    /// for the shadowing/substitution/error-propagation semantics.
+    pub fn all_methods(&self) -> Result<Vec<(MethodName, Arc<ClassElt<R>>)>> {
+        self.class
+            .methods
+            .iter()
+            .filter_map(|(&name, _)| match self.get_method(name) {
+                Ok(Some(elt)) => Some(Ok((name, elt))),
+                Ok(None) => None,
+                Err(e) => Some(Err(e)),
+            })
+            .collect()
+    }
+
+    /// Every static method declared on or inherited by this class. See
+    /// `all_props` for the shadowing/substitution/error-propagation
+    /// semantics.
+    pub fn all_static_methods(&self) -> Result<Vec<(MethodName, Arc<ClassElt<R>>)>> {
+        self.class
+            .static_methods
+            .iter()
+            .filter_map(|(&name, _)| match self.get_static_method(name) {
+                Ok(Some(elt)) => Some(Ok((name, elt))),
+                Ok(None) => None,
+                Err(e) => Some(Err(e)),
+            })
+            .collect()
+    }
+
+    /// Every static property declared on or inherited by this class. See
+    /// `all_props` for the shadowing/substitution/error-propagation
+    /// semantics.
+    pub fn all_static_props(&self) -> Result<Vec<(PropName, Arc<ClassElt<R>>)>> {
+        self.class
+            .static_props
+            .iter()
+            .filter_map(|(&name, _)| match self.get_static_prop(name) {
+                Ok(Some(elt)) => Some(Ok((name, elt))),
+                Ok(None) => None,
+                Err(e) => Some(Err(e)),
+            })
+            .collect()
+    }
+
+    /// Every class constant declared on or inherited by this class,
+    /// including abstract ones. See `all_props` for the
+    /// shadowing/substitution/error-propagation semantics.
+    pub fn all_consts(&self) -> Result<Vec<(ClassConstName, Arc<ClassElt<R>>)>> {
+        self.class
+            .consts
+            .iter()
+            .filter_map(|(&name, _)| match self.get_class_const(name) {
+                Ok(Some(elt)) => Some(Ok((name, elt))),
+                Ok(None) => None,
+                Err(e) => Some(Err(e)),
+            })
+            .collect()
+    }
+
+    /// Every type constant declared on or inherited by this class, including
+    /// abstract ones. See `all_props` for the
+    /// shadowing/substitution/error-propagation semantics.
+    pub fn all_type_consts(&self) -> Result<Vec<(TypeConstName, Arc<ClassElt<R>>)>> {
+        self.class
+            .type_consts
+            .iter()
+            .filter_map(|(&name, _)| match self.get_type_const(name) {
+                Ok(Some(elt)) => Some(Ok((name, elt))),
+                Ok(None) => None,
+                Err(e) => Some(Err(e)),
+            })
+            .collect()
+    }
+
+    /// Evict a single member from the cache, so that the next lookup
+    /// re-fetches it (and re-applies substitution) from the
+    /// `FoldedDeclProvider`. `origin` must be the member's origin class (the
+    /// same one the member was cached under, per `remember_origin`), since
+    /// this also prunes the `by_origin` reverse index for it; getting this
+    /// wrong leaves a stale `Member` behind that `invalidate_origin` will
+    /// later try to evict a second time (harmless, but a sign of a caller
+    /// bug). Used directly by an incremental typechecker when it knows the
+    /// shallow decl of exactly one member (not its whole origin class) has
+    /// changed; when a whole origin class changes, use `invalidate_origin`
+    /// instead so the reverse index stays in sync in one step.
+    pub fn invalidate_member(&self, origin: TypeName, member: Member) {
+        self.evict(member);
+        if let Some(mut members) = self.members.by_origin.get_mut(&origin) {
+            members.retain(|&m| m != member);
+        }
+        self.bump_epoch();
+    }
+
+    /// Evict every cached member that was fetched from `origin`. This is the
+    /// hook a `TypingDeclProvider` should call when it notices (e.g. by
+    /// reparsing a file) that `origin`'s shallow decl has changed: every
+    /// downstream `ClassType` that inherited members from `origin` can drop
+    /// just those members instead of its entire cache.
+    pub fn invalidate_origin(&self, origin: TypeName) {
+        if let Some((_, members)) = self.members.by_origin.remove(&origin) {
+            for member in members {
+                self.evict(member);
+            }
+        }
+        self.bump_epoch();
+    }
+
+    // Remove `member` from whichever cache map/slot holds it, without
+    // touching `by_origin`. Shared by `invalidate_member` (which also prunes
+    // the one `by_origin` entry for the member's origin) and
+    // `invalidate_origin` (which has already removed the whole `by_origin`
+    // entry for its origin, so pruning each member individually would be
+    // redundant).
+    fn evict(&self, member: Member) {
+        match member {
+            Member::Prop(name) => {
+                self.members.props.remove(&name);
+            }
+            Member::StaticProp(name) => {
+                self.members.static_props.remove(&name);
+            }
+            Member::Method(name) => {
+                self.members.methods.remove(&name);
+            }
+            Member::StaticMethod(name) => {
+                self.members.static_methods.remove(&name);
+            }
+            Member::Constructor => {
+                *self.members.constructor.write().unwrap() = None;
+            }
+            Member::Const(name) => {
+                self.members.consts.remove(&name);
+            }
+            Member::TypeConst(name) => {
+                self.members.type_consts.remove(&name);
+            }
+        }
+    }
+
+    /// Evict every cached member. Used when a class's entire shallow decl (or
+    /// the shallow decl of one of its ancestors) may have changed and we
+    /// don't want to bother diffing which individual members were affected.
+    pub fn invalidate_all(&self) {
+        self.members.props.clear();
+        self.members.static_props.clear();
+        self.members.methods.clear();
+        self.members.static_methods.clear();
+        *self.members.constructor.write().unwrap() = None;
+        self.members.consts.clear();
+        self.members.type_consts.clear();
+        self.members.by_origin.clear();
+        self.bump_epoch();
+    }
+
+    // Record that `member` (freshly inserted into the cache) came from
+    // `origin`, so `invalidate_origin` can find it later.
+    fn remember_origin(&self, origin: TypeName, member: Member) {
+        self.members
+            .by_origin
+            .entry(origin)
+            .or_default()
+            .push(member);
+    }
+
+    fn epoch(&self) -> u64 {
+        self.members.epoch.load(Ordering::SeqCst)
+    }
+
+    fn bump_epoch(&self) {
+        self.members.epoch.fetch_add(1, Ordering::SeqCst);
+    }
+
     fn fetch_all_members(&self) -> Result<()> {
         for (&prop, _) in self.class.props.iter() {
             self.get_prop(prop)?;
@@ -83,18 +334,129 @@ impl<R: Reason> ClassType<R> {
             self.get_static_method(method)?;
         }
         self.get_constructor()?;
+        for (&const_name, _) in self.class.consts.iter() {
+            self.get_class_const(const_name)?;
+        }
+        for (&const_name, _) in self.class.type_consts.iter() {
+            self.get_type_const(const_name)?;
+        }
         Ok(())
     }
 
-    // Invariant violation: we expect our provider to provide member types for any
-    // member from a FoldedClass it returned. See docs for `FoldedDeclProvider`.
-    // c.f. OCaml exception `Decl_heap_elems_bug`
-    fn member_type_missing<T>(&self, kind: &str, origin: TypeName, name: impl AsRef<str>) -> T {
-        panic!(
-            "Could not find {kind} {origin}::{} (inherited by {})",
-            name.as_ref(),
-            self.class.name
-        );
+    // We expect our provider to provide member types for any member from a
+    // FoldedClass it returned (see docs for `FoldedDeclProvider`), so this
+    // indicates a bug in the provider or a race with invalidation. Rather
+    // than crash the whole process (as the OCaml exception
+    // `Decl_heap_elems_bug` does), we surface it as a recoverable error so a
+    // long-lived server can log it and move on to the next request.
+    fn member_type_missing(
+        &self,
+        kind: &'static str,
+        origin: TypeName,
+        name: impl AsRef<str>,
+        hint: Option<&'static str>,
+    ) -> Error {
+        Error::MemberTypeMissing {
+            kind,
+            origin,
+            name: name.as_ref().into(),
+            inherited_by: self.class.name,
+            hint,
+        }
+    }
+
+    // Members are declared using the type parameters of the class which
+    // declares them (`origin`). To expose them through `self.class` (which
+    // may be a descendant of `origin`), we must substitute `origin`'s type
+    // parameters for the type arguments flowed to it along the inheritance
+    // chain, which `self.class.substs` records for every ancestor.
+    fn instantiate(&self, origin: TypeName, ty: DeclTy<R>) -> DeclTy<R> {
+        if origin == self.class.name {
+            return ty;
+        }
+        match self.class.substs.get(&origin) {
+            Some(subst) => Self::substitute(&ty, subst),
+            None => ty,
+        }
+    }
+
+    // Recurses into every `DeclTy_` variant that carries a nested `DeclTy` or
+    // `Vec<DeclTy>`, not just bare `Tgeneric` nodes, since an inherited
+    // member's type parameter is far more likely to appear nested (e.g.
+    // `vec<T>`, `?T`, a method parameter or return type of type `T`) than as
+    // the member's entire type.
+    fn substitute(ty: &DeclTy<R>, subst: &Subst<R>) -> DeclTy<R> {
+        let node = match ty.node() {
+            DeclTy_::Tgeneric(name, targs) => {
+                if let Some(substituted_ty) = subst.get(name) {
+                    return substituted_ty.clone();
+                }
+                DeclTy_::Tgeneric(name.clone(), Self::substitute_all(targs, subst))
+            }
+            DeclTy_::Tapply(class_name, targs) => {
+                DeclTy_::Tapply(class_name.clone(), Self::substitute_all(targs, subst))
+            }
+            DeclTy_::Toption(inner) => DeclTy_::Toption(Self::substitute(inner, subst)),
+            DeclTy_::Tlike(inner) => DeclTy_::Tlike(Self::substitute(inner, subst)),
+            DeclTy_::Ttuple(targs) => DeclTy_::Ttuple(Self::substitute_all(targs, subst)),
+            DeclTy_::Tunion(targs) => DeclTy_::Tunion(Self::substitute_all(targs, subst)),
+            DeclTy_::Tintersection(targs) => {
+                DeclTy_::Tintersection(Self::substitute_all(targs, subst))
+            }
+            DeclTy_::Tfun(ft) => DeclTy_::Tfun(FunType {
+                params: ft
+                    .params
+                    .iter()
+                    .map(|param| FunParam {
+                        type_: Self::substitute(&param.type_, subst),
+                        ..param.clone()
+                    })
+                    .collect(),
+                ret: Self::substitute(&ft.ret, subst),
+                ..ft.clone()
+            }),
+            DeclTy_::Tshape(shape) => DeclTy_::Tshape(ShapeType {
+                fields: shape
+                    .fields
+                    .iter()
+                    .map(|(field_name, field)| {
+                        (
+                            field_name.clone(),
+                            ShapeFieldType {
+                                ty: Self::substitute(&field.ty, subst),
+                                ..field.clone()
+                            },
+                        )
+                    })
+                    .collect(),
+                ..shape.clone()
+            }),
+            // `this::TOutput`-style access into a (possibly still-generic)
+            // root type, e.g. a method parameter typed `T::TOutput` for a
+            // class generic `T`.
+            DeclTy_::Taccess(root, id) => {
+                DeclTy_::Taccess(Self::substitute(root, subst), id.clone())
+            }
+            // A newtype's type arguments and "as" bound can both mention the
+            // class's generics, e.g. `newtype Box<T> as T`.
+            DeclTy_::Tnewtype(name, targs, as_ty) => DeclTy_::Tnewtype(
+                name.clone(),
+                Self::substitute_all(targs, subst),
+                Self::substitute(as_ty, subst),
+            ),
+            // Leaf variants that carry no nested `DeclTy` (primitives, `this`,
+            // `mixed`, `nonnull`, `dynamic`, error/placeholder types, ...)
+            // have nothing to substitute into.
+            _ => return ty.clone(),
+        };
+        DeclTy::new(ty.reason().clone(), node)
+    }
+
+    fn substitute_all(targs: &[DeclTy<R>], subst: &Subst<R>) -> Vec<DeclTy<R>> {
+        targs
+            .iter()
+            .map(|targ| Self::substitute(targ, subst))
+            .collect()
     }
 }
 
@@ -108,13 +470,26 @@ impl<R: Reason> Class<R> for ClassType<R> {
             None => return Ok(None),
         };
         let origin = folded_elt.origin;
+        let epoch = self.epoch();
         let ty = self
             .provider
             .get_shallow_property_type(origin, name)?
-            .unwrap_or_else(|| self.member_type_missing("property", origin, name));
-        // TODO: perform substitutions on ty
+            .ok_or_else(|| {
+                let hint = self
+                    .class
+                    .static_props
+                    .contains_key(&name)
+                    .then_some("a static member with this name exists");
+                self.member_type_missing("property", origin, name, hint)
+            })?;
+        let ty = self.instantiate(origin, ty);
         let class_elt = Arc::new(ClassElt::new(folded_elt, ty));
-        self.members.props.insert(name, Arc::clone(&class_elt));
+        // If an invalidation raced this fetch, don't resurrect a cache entry
+        // for what may now be a stale type; just hand this one result back.
+        if self.epoch() == epoch {
+            self.members.props.insert(name, Arc::clone(&class_elt));
+            self.remember_origin(origin, Member::Prop(name));
+        }
         Ok(Some(class_elt))
     }
 
@@ -127,15 +502,26 @@ impl<R: Reason> Class<R> for ClassType<R> {
             None => return Ok(None),
         };
         let origin = folded_elt.origin;
+        let epoch = self.epoch();
         let ty = self
             .provider
             .get_shallow_static_property_type(origin, name)?
-            .unwrap_or_else(|| self.member_type_missing("static property", origin, name));
-        // TODO: perform substitutions on ty
+            .ok_or_else(|| {
+                let hint = self
+                    .class
+                    .props
+                    .contains_key(&name)
+                    .then_some("an instance member with this name exists");
+                self.member_type_missing("static property", origin, name, hint)
+            })?;
+        let ty = self.instantiate(origin, ty);
         let class_elt = Arc::new(ClassElt::new(folded_elt, ty));
-        self.members
-            .static_props
-            .insert(name, Arc::clone(&class_elt));
+        if self.epoch() == epoch {
+            self.members
+                .static_props
+                .insert(name, Arc::clone(&class_elt));
+            self.remember_origin(origin, Member::StaticProp(name));
+        }
         Ok(Some(class_elt))
     }
 
@@ -148,13 +534,24 @@ impl<R: Reason> Class<R> for ClassType<R> {
             None => return Ok(None),
         };
         let origin = folded_elt.origin;
+        let epoch = self.epoch();
         let ty = self
             .provider
             .get_shallow_method_type(origin, name)?
-            .unwrap_or_else(|| self.member_type_missing("method", origin, name));
-        // TODO: perform substitutions on ty
+            .ok_or_else(|| {
+                let hint = self
+                    .class
+                    .static_methods
+                    .contains_key(&name)
+                    .then_some("a static member with this name exists");
+                self.member_type_missing("method", origin, name, hint)
+            })?;
+        let ty = self.instantiate(origin, ty);
         let class_elt = Arc::new(ClassElt::new(folded_elt, ty));
-        self.members.methods.insert(name, Arc::clone(&class_elt));
+        if self.epoch() == epoch {
+            self.members.methods.insert(name, Arc::clone(&class_elt));
+            self.remember_origin(origin, Member::Method(name));
+        }
         Ok(Some(class_elt))
     }
 
@@ -167,38 +564,237 @@ impl<R: Reason> Class<R> for ClassType<R> {
             None => return Ok(None),
         };
         let origin = folded_elt.origin;
+        let epoch = self.epoch();
         let ty = self
             .provider
             .get_shallow_static_method_type(origin, name)?
-            .unwrap_or_else(|| self.member_type_missing("static method", origin, name));
-        // TODO: perform substitutions on ty
+            .ok_or_else(|| {
+                let hint = self
+                    .class
+                    .methods
+                    .contains_key(&name)
+                    .then_some("an instance member with this name exists");
+                self.member_type_missing("static method", origin, name, hint)
+            })?;
+        let ty = self.instantiate(origin, ty);
         let class_elt = Arc::new(ClassElt::new(folded_elt, ty));
-        self.members
-            .static_methods
-            .insert(name, Arc::clone(&class_elt));
+        if self.epoch() == epoch {
+            self.members
+                .static_methods
+                .insert(name, Arc::clone(&class_elt));
+            self.remember_origin(origin, Member::StaticMethod(name));
+        }
         Ok(Some(class_elt))
     }
 
     fn get_constructor(&self) -> Result<Option<Arc<ClassElt<R>>>> {
-        Ok(self
-            .members
-            .constructor
-            .get_or_try_init::<_, Error>(|| {
-                let folded_elt = match &self.class.constructor {
-                    Some(fe) => fe,
-                    None => return Ok(None),
-                };
-                let origin = folded_elt.origin;
-                let ty = self
-                    .provider
-                    .get_shallow_constructor_type(origin)?
-                    .unwrap_or_else(|| {
-                        self.member_type_missing("constructor", origin, "__construct")
-                    });
-                // TODO: perform substitutions on ty
-                Ok(Some(Arc::new(ClassElt::new(folded_elt, ty))))
-            })?
-            .as_ref()
-            .map(Arc::clone))
+        if let Some(cached) = self.members.constructor.read().unwrap().clone() {
+            return Ok(cached);
+        }
+        let mut constructor = self.members.constructor.write().unwrap();
+        if let Some(cached) = constructor.clone() {
+            return Ok(cached);
+        }
+        let folded_elt = match &self.class.constructor {
+            Some(fe) => fe,
+            None => {
+                *constructor = Some(None);
+                return Ok(None);
+            }
+        };
+        let origin = folded_elt.origin;
+        let epoch = self.epoch();
+        let ty = self
+            .provider
+            .get_shallow_constructor_type(origin)?
+            .ok_or_else(|| self.member_type_missing("constructor", origin, "__construct", None))?;
+        let ty = self.instantiate(origin, ty);
+        let class_elt = Arc::new(ClassElt::new(folded_elt, ty));
+        if self.epoch() == epoch {
+            *constructor = Some(Some(Arc::clone(&class_elt)));
+            drop(constructor);
+            self.remember_origin(origin, Member::Constructor);
+        }
+        Ok(Some(class_elt))
+    }
+
+    fn get_class_const(&self, name: ClassConstName) -> Result<Option<Arc<ClassElt<R>>>> {
+        if let Some(class_elt) = self.members.consts.get(&name) {
+            return Ok(Some(Arc::clone(&class_elt)));
+        }
+        let folded_elt = match self.class.consts.get(&name) {
+            Some(fe) => fe,
+            None => return Ok(None),
+        };
+        let origin = folded_elt.origin;
+        let epoch = self.epoch();
+        let ty = self
+            .provider
+            .get_shallow_class_const_type(origin, name)?
+            .ok_or_else(|| self.member_type_missing("class constant", origin, name, None))?;
+        let ty = self.instantiate(origin, ty);
+        let class_elt = Arc::new(ClassElt::new(folded_elt, ty));
+        if self.epoch() == epoch {
+            self.members.consts.insert(name, Arc::clone(&class_elt));
+            self.remember_origin(origin, Member::Const(name));
+        }
+        Ok(Some(class_elt))
+    }
+
+    fn get_type_const(&self, name: TypeConstName) -> Result<Option<Arc<ClassElt<R>>>> {
+        if let Some(class_elt) = self.members.type_consts.get(&name) {
+            return Ok(Some(Arc::clone(&class_elt)));
+        }
+        let folded_elt = match self.class.type_consts.get(&name) {
+            Some(fe) => fe,
+            None => return Ok(None),
+        };
+        let origin = folded_elt.origin;
+        let epoch = self.epoch();
+        let ty = self
+            .provider
+            .get_shallow_type_const_type(origin, name)?
+            .ok_or_else(|| self.member_type_missing("type constant", origin, name, None))?;
+        let ty = self.instantiate(origin, ty);
+        let class_elt = Arc::new(ClassElt::new(folded_elt, ty));
+        if self.epoch() == epoch {
+            self.members
+                .type_consts
+                .insert(name, Arc::clone(&class_elt));
+            self.remember_origin(origin, Member::TypeConst(name));
+        }
+        Ok(Some(class_elt))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reason::NReason;
+
+    fn ty(node: DeclTy_<NReason>) -> DeclTy<NReason> {
+        DeclTy::new(NReason::default(), node)
+    }
+
+    fn generic(name: &str) -> DeclTy<NReason> {
+        ty(DeclTy_::Tgeneric(name.into(), vec![]))
+    }
+
+    #[test]
+    fn substitute_rewrites_generic_nested_in_option_and_apply() {
+        let mut subst = Subst::new();
+        subst.insert(TypeName::new("T"), generic("int"));
+        let input = ty(DeclTy_::Toption(ty(DeclTy_::Tapply(
+            TypeName::new("vec"),
+            vec![generic("T")],
+        ))));
+
+        let result = ClassType::<NReason>::substitute(&input, &subst);
+
+        match result.node() {
+            DeclTy_::Toption(inner) => match inner.node() {
+                DeclTy_::Tapply(name, targs) => {
+                    assert_eq!(*name, TypeName::new("vec"));
+                    assert_eq!(targs.len(), 1);
+                    assert!(matches!(targs[0].node(), DeclTy_::Tgeneric(n, _) if n == "int"));
+                }
+                other => panic!("expected Tapply, got {other:?}"),
+            },
+            other => panic!("expected Toption, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn substitute_leaves_unmapped_generic_untouched() {
+        let subst = Subst::new();
+        let input = generic("T");
+
+        let result = ClassType::<NReason>::substitute(&input, &subst);
+
+        assert!(matches!(result.node(), DeclTy_::Tgeneric(n, _) if n == "T"));
+    }
+
+    // Invalidation bookkeeping (`evict`/`remember_origin`/`by_origin`) only
+    // touches `self.members`, never `self.class` or `self.provider`, so a
+    // `ClassType` built around `unimplemented!()` placeholders for those two
+    // fields is a faithful-enough fixture for these tests: exercising it
+    // would be a bug in the test, not a missing fixture.
+    fn class_type_for_invalidation_tests() -> ClassType<NReason> {
+        ClassType {
+            provider: unimplemented!(),
+            class: unimplemented!(),
+            members: EagerMembers::new(),
+        }
+    }
+
+    #[test]
+    fn invalidate_member_prunes_only_its_own_by_origin_entry() {
+        let class_type = class_type_for_invalidation_tests();
+        let origin = TypeName::new("Base");
+        class_type.remember_origin(origin, Member::Prop(PropName::new("a")));
+        class_type.remember_origin(origin, Member::Prop(PropName::new("b")));
+
+        class_type.invalidate_member(origin, Member::Prop(PropName::new("a")));
+
+        let remaining = class_type.members.by_origin.get(&origin).unwrap();
+        assert_eq!(remaining.len(), 1);
+        assert!(matches!(remaining[0], Member::Prop(name) if name == PropName::new("b")));
+    }
+
+    #[test]
+    fn invalidate_origin_clears_only_that_origins_entry() {
+        let class_type = class_type_for_invalidation_tests();
+        let base = TypeName::new("Base");
+        let other = TypeName::new("Other");
+        class_type.remember_origin(base, Member::Method(MethodName::new("m")));
+        class_type.remember_origin(other, Member::Method(MethodName::new("n")));
+
+        class_type.invalidate_origin(base);
+
+        assert!(class_type.members.by_origin.get(&base).is_none());
+        assert!(class_type.members.by_origin.get(&other).is_some());
+    }
+
+    #[test]
+    fn invalidate_all_clears_every_origin() {
+        let class_type = class_type_for_invalidation_tests();
+        let base = TypeName::new("Base");
+        let other = TypeName::new("Other");
+        class_type.remember_origin(base, Member::Method(MethodName::new("m")));
+        class_type.remember_origin(other, Member::Method(MethodName::new("n")));
+
+        class_type.invalidate_all();
+
+        assert!(class_type.members.by_origin.is_empty());
+    }
+
+    #[test]
+    fn substitute_recurses_into_taccess_and_tnewtype() {
+        let mut subst = Subst::new();
+        subst.insert(TypeName::new("T"), generic("int"));
+
+        let access = ty(DeclTy_::Taccess(
+            generic("T"),
+            TypeConstName::new("TOutput"),
+        ));
+        match ClassType::<NReason>::substitute(&access, &subst).node() {
+            DeclTy_::Taccess(root, _) => {
+                assert!(matches!(root.node(), DeclTy_::Tgeneric(n, _) if n == "int"));
+            }
+            other => panic!("expected Taccess, got {other:?}"),
+        }
+
+        let newtype = ty(DeclTy_::Tnewtype(
+            TypeName::new("Box"),
+            vec![generic("T")],
+            generic("T"),
+        ));
+        match ClassType::<NReason>::substitute(&newtype, &subst).node() {
+            DeclTy_::Tnewtype(_, targs, as_ty) => {
+                assert!(matches!(targs[0].node(), DeclTy_::Tgeneric(n, _) if n == "int"));
+                assert!(matches!(as_ty.node(), DeclTy_::Tgeneric(n, _) if n == "int"));
+            }
+            other => panic!("expected Tnewtype, got {other:?}"),
+        }
     }
 }
@@ -0,0 +1,73 @@
+// Copyright (c) Facebook, Inc. and its affiliates.
+//
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the "hack" directory of this source tree.
+
+mod defs;
+
+pub use defs::{ClassType, Member};
+
+use crate::reason::Reason;
+use crate::typing_defs::ClassElt;
+use pos::{ClassConstName, MethodName, PropName, TypeConstName, TypeName};
+use std::fmt;
+use std::sync::Arc;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// A resolved, typed view of a class's members. Implementations build this
+/// view on top of a `FoldedClass` by looking up each member's type (which
+/// folded decls don't themselves carry) from a `FoldedDeclProvider`.
+pub trait Class<R: Reason> {
+    fn get_prop(&self, name: PropName) -> Result<Option<Arc<ClassElt<R>>>>;
+    fn get_static_prop(&self, name: PropName) -> Result<Option<Arc<ClassElt<R>>>>;
+    fn get_method(&self, name: MethodName) -> Result<Option<Arc<ClassElt<R>>>>;
+    fn get_static_method(&self, name: MethodName) -> Result<Option<Arc<ClassElt<R>>>>;
+    fn get_constructor(&self) -> Result<Option<Arc<ClassElt<R>>>>;
+    fn get_class_const(&self, name: ClassConstName) -> Result<Option<Arc<ClassElt<R>>>>;
+    fn get_type_const(&self, name: TypeConstName) -> Result<Option<Arc<ClassElt<R>>>>;
+}
+
+/// Errors produced while resolving a class's members. Unlike the OCaml
+/// `Decl_heap_elems_bug` exception this is a recoverable error: a long-lived
+/// typechecker server can catch it, log it, and move on to the next request
+/// instead of crashing.
+#[derive(Debug, Clone)]
+pub enum Error {
+    /// The `FoldedDeclProvider` claimed (via the `FoldedClass` it returned)
+    /// that `origin` declares a member named `name`, but failed to supply
+    /// its type when asked. `hint`, when present, flags a likely cause: a
+    /// static member was looked up as an instance member, or vice versa.
+    MemberTypeMissing {
+        kind: &'static str,
+        origin: TypeName,
+        name: String,
+        inherited_by: TypeName,
+        hint: Option<&'static str>,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MemberTypeMissing {
+                kind,
+                origin,
+                name,
+                inherited_by,
+                hint,
+            } => {
+                write!(
+                    f,
+                    "Could not find {kind} {origin}::{name} (inherited by {inherited_by})"
+                )?;
+                if let Some(hint) = hint {
+                    write!(f, ": {hint}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}